@@ -1,6 +1,11 @@
 use dprint_core::configuration::NewLineKind;
-use dprint_plugin_markdown::{configuration::Configuration, format_text};
-use rustler::{Atom, Term};
+use dprint_markdown_formatter_core::{
+    apply_format, build_code_block_formatters, check_format, format_diff, resolve_code_block, CheckOutcome,
+    CodeBlockErrors, LineHunk, LineRange, PendingCallback,
+};
+use dprint_plugin_markdown::configuration::Configuration;
+use rayon::prelude::*;
+use rustler::{Atom, Encoder, Env, ResourceArc, Term};
 use std::collections::HashMap;
 
 // Define atom constants
@@ -11,6 +16,8 @@ rustler::atoms! {
     strong_kind,
     new_line_kind,
     unordered_list_kind,
+    code_block_formatters,
+    file_lines,
     always,
     never,
     maintain,
@@ -20,25 +27,161 @@ rustler::atoms! {
     lf,
     crlf,
     dashes,
+    unchanged,
+    would_reformat,
+    format_error,
+    code_block_errors,
+}
+
+/// Error returned from `check_markdown`: either the document genuinely needs
+/// reformatting (the non-error outcome `format_markdown` would also
+/// produce), a code block failed to format, or the config/formatting itself
+/// is broken. Keeping these distinct means a caller can't mistake "your
+/// code_block_formatters entry is invalid" for "just run the formatter".
+enum CheckError {
+    FormatError(String),
+    WouldReformat(u32),
+    CodeBlockErrors(CodeBlockErrors),
+}
+
+impl Encoder for CheckError {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        match self {
+            CheckError::FormatError(reason) => (format_error(), reason.as_str()).encode(env),
+            CheckError::WouldReformat(count) => (would_reformat(), *count).encode(env),
+            CheckError::CodeBlockErrors(errors) => (code_block_errors(), errors.as_slice()).encode(env),
+        }
+    }
 }
 
 /// Simple NIF function that receives a config map from Elixir
 /// The map contains only the 6 dprint-related fields (no format_module_attributes)
 /// Elixir is the single source of truth for configuration validation
-#[rustler::nif]
-fn format_markdown(text: String, config: HashMap<Atom, Term>) -> Result<String, String> {
+///
+/// Runs on a dirty scheduler because formatting a fenced code block may call
+/// back into Elixir via an MFA formatter and block the thread waiting for
+/// `resolve_code_block_callback`, which must never happen on a normal
+/// scheduler thread. Returns the formatted text alongside any code blocks
+/// that failed to format (and were left verbatim) as `(language, reason)`
+/// pairs, rather than failing the whole document over one bad block.
+#[rustler::nif(schedule = "DirtyIo")]
+fn format_markdown(text: String, config: HashMap<Atom, Term>) -> Result<(String, CodeBlockErrors), String> {
     // Early return for empty text
     if text.is_empty() {
-        return Ok(text);
+        return Ok((text, Vec::new()));
     }
 
+    // Sub-formatters for fenced code blocks are looked up by language tag before
+    // we hand the callback to dprint, since `config` is consumed below.
+    let formatters = build_code_block_formatters(&config, code_block_formatters())?;
+    let ranges = build_file_lines(&config)?;
+
     // Convert config map to dprint Configuration
     let dprint_config = build_dprint_config(config)?;
 
-    // Format the text using dprint-plugin-markdown
-    format_text(&text, &dprint_config, |_, _, _| Ok(None))
-        .map_err(|e| format!("Formatting failed: {e}"))
-        .map(|result| result.unwrap_or(text))
+    apply_format(&text, &dprint_config, &formatters, &ranges)
+}
+
+/// Formats many documents against one shared configuration, amortizing the
+/// cost of crossing the NIF boundary and building the `Configuration` once.
+/// Runs on a dirty CPU scheduler and fans entries out over a `rayon` thread
+/// pool; one entry's failure is reported alongside the others rather than
+/// aborting the whole batch.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn format_markdown_batch(
+    entries: Vec<(String, String)>,
+    config: HashMap<Atom, Term>,
+) -> Result<Vec<(String, Result<(String, CodeBlockErrors), String>)>, String> {
+    let formatters = build_code_block_formatters(&config, code_block_formatters())?;
+    let ranges = build_file_lines(&config)?;
+    let dprint_config = build_dprint_config(config)?;
+
+    let results = entries
+        .into_par_iter()
+        .map(|(id, text)| {
+            let result = if text.is_empty() {
+                Ok((text, Vec::new()))
+            } else {
+                apply_format(&text, &dprint_config, &formatters, &ranges)
+            };
+            (id, result)
+        })
+        .collect();
+
+    Ok(results)
+}
+
+/// Parses the `file_lines` config entry: an optional list of 1-based
+/// inclusive line ranges restricting where formatting is allowed to land.
+/// Missing or empty means "format everything".
+fn build_file_lines(map: &HashMap<Atom, Term>) -> Result<Vec<LineRange>, String> {
+    match map.get(&file_lines()) {
+        None => Ok(Vec::new()),
+        Some(term) => term
+            .decode::<Vec<LineRange>>()
+            .map_err(|_| "Invalid file_lines".to_string()),
+    }
+}
+
+/// Resolves a pending Elixir code-block callback, unblocking the dirty
+/// scheduler thread that dispatched it. `result` is `{:ok, formatted_or_nil}`
+/// on success or `{:error, reason}` if the Elixir-side formatter failed.
+#[rustler::nif]
+fn resolve_code_block_callback(pending: ResourceArc<PendingCallback>, result: Result<Option<String>, String>) {
+    resolve_code_block(pending, result);
+}
+
+/// Reports whether `text` is already formatted, without paying to ship the
+/// rewritten document back across the NIF boundary. Returns `{:ok, :unchanged}`
+/// when formatting the whole document would produce identical output,
+/// `{:error, {:would_reformat, differing_line_count}}` when it would change
+/// the text, `{:error, {:code_block_errors, [{language, reason}]}}` when a
+/// fenced code block failed to format, or `{:error, {:format_error, reason}}`
+/// for config or formatting failures. The `file_lines` entry is neither read
+/// nor validated: the verdict always covers the whole document, so a change
+/// outside the requested ranges still counts as `:would_reformat`.
+/// Useful for CI/pre-commit gates over many files
+/// that only need a pass/fail result. Runs on a dirty scheduler for the same
+/// reason `format_markdown` does: code block formatting can call back into
+/// Elixir and block.
+#[rustler::nif(schedule = "DirtyIo")]
+fn check_markdown(text: String, config: HashMap<Atom, Term>) -> Result<Atom, CheckError> {
+    if text.is_empty() {
+        return Ok(unchanged());
+    }
+
+    let formatters =
+        build_code_block_formatters(&config, code_block_formatters()).map_err(CheckError::FormatError)?;
+    let dprint_config = build_dprint_config(config).map_err(CheckError::FormatError)?;
+
+    let (outcome, errors) =
+        check_format(&text, &dprint_config, &formatters).map_err(CheckError::FormatError)?;
+    if !errors.is_empty() {
+        return Err(CheckError::CodeBlockErrors(errors));
+    }
+
+    match outcome {
+        CheckOutcome::Unchanged => Ok(unchanged()),
+        CheckOutcome::WouldReformat(count) => Err(CheckError::WouldReformat(count)),
+    }
+}
+
+/// Formats `text` and returns the changes as a list of line hunks rather than
+/// the whole document, so editors and review tools can apply or display
+/// edits precisely without re-diffing the full text themselves, alongside any
+/// code blocks that failed to format as `(language, reason)` pairs. Returns
+/// an empty hunk list when formatting wouldn't change anything.
+/// Dirty-scheduled for the same reason as `format_markdown`.
+#[rustler::nif(schedule = "DirtyIo")]
+fn format_markdown_diff(text: String, config: HashMap<Atom, Term>) -> Result<(Vec<LineHunk>, CodeBlockErrors), String> {
+    if text.is_empty() {
+        return Ok((Vec::new(), Vec::new()));
+    }
+
+    let formatters = build_code_block_formatters(&config, code_block_formatters())?;
+    let dprint_config = build_dprint_config(config)?;
+
+    format_diff(&text, &dprint_config, &formatters)
 }
 
 /// Build dprint Configuration from config map provided by Elixir
@@ -71,9 +214,7 @@ fn build_dprint_config(map: HashMap<Atom, Term>) -> Result<Configuration, String
 }
 
 /// Build text wrap configuration from config map
-fn build_text_wrap(
-    map: &HashMap<Atom, Term>,
-) -> Result<dprint_plugin_markdown::configuration::TextWrap, String> {
+fn build_text_wrap(map: &HashMap<Atom, Term>) -> Result<dprint_plugin_markdown::configuration::TextWrap, String> {
     let wrap_atom = map
         .get(&text_wrap())
         .ok_or("Missing text_wrap")?
@@ -89,9 +230,7 @@ fn build_text_wrap(
 }
 
 /// Build emphasis kind configuration from config map
-fn build_emphasis_kind(
-    map: &HashMap<Atom, Term>,
-) -> Result<dprint_plugin_markdown::configuration::EmphasisKind, String> {
+fn build_emphasis_kind(map: &HashMap<Atom, Term>) -> Result<dprint_plugin_markdown::configuration::EmphasisKind, String> {
     let kind_atom = map
         .get(&emphasis_kind())
         .ok_or("Missing emphasis_kind")?
@@ -99,20 +238,14 @@ fn build_emphasis_kind(
         .map_err(|_| "Invalid emphasis_kind")?;
 
     match kind_atom {
-        atom if atom == asterisks() => {
-            Ok(dprint_plugin_markdown::configuration::EmphasisKind::Asterisks)
-        }
-        atom if atom == underscores() => {
-            Ok(dprint_plugin_markdown::configuration::EmphasisKind::Underscores)
-        }
+        atom if atom == asterisks() => Ok(dprint_plugin_markdown::configuration::EmphasisKind::Asterisks),
+        atom if atom == underscores() => Ok(dprint_plugin_markdown::configuration::EmphasisKind::Underscores),
         _ => Err("Invalid emphasis_kind value".to_string()),
     }
 }
 
 /// Build strong kind configuration from config map
-fn build_strong_kind(
-    map: &HashMap<Atom, Term>,
-) -> Result<dprint_plugin_markdown::configuration::StrongKind, String> {
+fn build_strong_kind(map: &HashMap<Atom, Term>) -> Result<dprint_plugin_markdown::configuration::StrongKind, String> {
     let kind_atom = map
         .get(&strong_kind())
         .ok_or("Missing strong_kind")?
@@ -120,12 +253,8 @@ fn build_strong_kind(
         .map_err(|_| "Invalid strong_kind")?;
 
     match kind_atom {
-        atom if atom == asterisks() => {
-            Ok(dprint_plugin_markdown::configuration::StrongKind::Asterisks)
-        }
-        atom if atom == underscores() => {
-            Ok(dprint_plugin_markdown::configuration::StrongKind::Underscores)
-        }
+        atom if atom == asterisks() => Ok(dprint_plugin_markdown::configuration::StrongKind::Asterisks),
+        atom if atom == underscores() => Ok(dprint_plugin_markdown::configuration::StrongKind::Underscores),
         _ => Err("Invalid strong_kind value".to_string()),
     }
 }
@@ -157,14 +286,14 @@ fn build_unordered_list_kind(
         .map_err(|_| "Invalid unordered_list_kind")?;
 
     match kind_atom {
-        atom if atom == dashes() => {
-            Ok(dprint_plugin_markdown::configuration::UnorderedListKind::Dashes)
-        }
-        atom if atom == asterisks() => {
-            Ok(dprint_plugin_markdown::configuration::UnorderedListKind::Asterisks)
-        }
+        atom if atom == dashes() => Ok(dprint_plugin_markdown::configuration::UnorderedListKind::Dashes),
+        atom if atom == asterisks() => Ok(dprint_plugin_markdown::configuration::UnorderedListKind::Asterisks),
         _ => Err("Invalid unordered_list_kind value".to_string()),
     }
 }
 
-rustler::init!("Elixir.DprintMarkdownFormatter.Native");
+fn load(env: Env, _info: Term) -> bool {
+    env.register::<PendingCallback>().is_ok()
+}
+
+rustler::init!("Elixir.DprintMarkdownFormatter.Native", load = load);
@@ -0,0 +1,202 @@
+use rustler::{Atom, LocalPid, OwnedEnv, ResourceArc, Term};
+use std::collections::HashMap;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+rustler::atoms! {
+    builtin,
+    mfa,
+    json,
+    toml,
+}
+
+/// How long `call_elixir_formatter` will wait for `resolve_code_block/2`
+/// before giving up on an Elixir callback that never replies.
+const CALLBACK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often the blocked thread wakes up to check whether the callback
+/// process is still alive, so a crashed callback is noticed well before
+/// `CALLBACK_TIMEOUT` elapses.
+const CALLBACK_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How a single fenced code block's language tag should be formatted.
+enum CodeBlockFormatter {
+    /// Formatted in-process by a bundled dprint plugin.
+    Builtin(BuiltinLanguage),
+    /// Formatted by calling back into an Elixir `{module, function}` pair.
+    Mfa {
+        pid: LocalPid,
+        module: Atom,
+        function: Atom,
+    },
+}
+
+/// Languages with a bundled dprint plugin, gated behind cargo features so
+/// callers only pay for the sub-formatters they actually use.
+enum BuiltinLanguage {
+    #[cfg(feature = "json")]
+    Json,
+    #[cfg(feature = "toml")]
+    Toml,
+}
+
+/// Map from fenced code block language tag (e.g. `"json"`, `"elixir"`) to the
+/// formatter that should handle it.
+pub struct CodeBlockFormatters(HashMap<String, CodeBlockFormatter>);
+
+/// Shared reply slot an Elixir callback resolves via `resolve_code_block/2`.
+/// The NIF thread blocks on the `Condvar` (with a timeout — see
+/// `call_elixir_formatter`) until the reply is filled in, so this must only
+/// be driven from a dirty scheduler, never a normal one.
+#[derive(Default, rustler::Resource)]
+pub struct PendingCallback(Mutex<Option<Result<Option<String>, String>>>, Condvar);
+
+/// Parses the `code_block_formatters` entry of a `HashMap<Atom, Term>` config,
+/// a map from language tag to either a builtin atom (`:json`, `:toml`) or an
+/// `{:mfa, pid, module, function}` tuple describing an Elixir callback.
+pub fn build_code_block_formatters(map: &HashMap<Atom, Term>, key: Atom) -> Result<CodeBlockFormatters, String> {
+    match map.get(&key) {
+        None => Ok(CodeBlockFormatters(HashMap::new())),
+        Some(&term) => formatters_from_term(term),
+    }
+}
+
+/// Parses the `code_block_formatters` entry out of a keyword-list `Term`
+/// config, already extracted by the caller as `Option<Term>`. Invalid or
+/// absent entries are treated as "no formatters configured", matching how
+/// the rest of the keyword-list config parses permissively.
+pub fn parse_code_block_formatters(spec: Option<Term>) -> CodeBlockFormatters {
+    match spec {
+        None => CodeBlockFormatters(HashMap::new()),
+        Some(term) => formatters_from_term(term).unwrap_or_else(|_| CodeBlockFormatters(HashMap::new())),
+    }
+}
+
+fn formatters_from_term(term: Term) -> Result<CodeBlockFormatters, String> {
+    let entries = term
+        .decode::<HashMap<String, Term>>()
+        .map_err(|_| "Invalid code_block_formatters")?;
+
+    let formatters = entries
+        .into_iter()
+        .map(|(language, spec)| Ok((language, decode_formatter_spec(spec)?)))
+        .collect::<Result<_, String>>()?;
+
+    Ok(CodeBlockFormatters(formatters))
+}
+
+fn decode_formatter_spec(spec: Term) -> Result<CodeBlockFormatter, String> {
+    if let Ok(kind) = spec.decode::<Atom>() {
+        return match kind {
+            #[cfg(feature = "json")]
+            atom if atom == json() => Ok(CodeBlockFormatter::Builtin(BuiltinLanguage::Json)),
+            #[cfg(feature = "toml")]
+            atom if atom == toml() => Ok(CodeBlockFormatter::Builtin(BuiltinLanguage::Toml)),
+            _ => Err("Unknown builtin code block formatter".to_string()),
+        };
+    }
+
+    let (tag, pid, module, function) = spec
+        .decode::<(Atom, LocalPid, Atom, Atom)>()
+        .map_err(|_| "Invalid code_block_formatters entry")?;
+
+    if tag != mfa() {
+        return Err("Invalid code_block_formatters entry".to_string());
+    }
+
+    Ok(CodeBlockFormatter::Mfa {
+        pid,
+        module,
+        function,
+    })
+}
+
+/// Formats one fenced code block, leaving it untouched when no formatter is
+/// registered for its language tag. Errors are returned rather than panicking
+/// so a single bad block doesn't abort the whole document. Callable from any
+/// OS thread (not just the NIF scheduler thread that received the call), so
+/// a batch NIF can drive this from a `rayon` worker.
+pub fn format_code_block(language: &str, text: &str, formatters: &CodeBlockFormatters) -> Result<Option<String>, String> {
+    match formatters.0.get(language) {
+        None => Ok(None),
+        Some(CodeBlockFormatter::Builtin(language)) => format_builtin(language, text),
+        Some(CodeBlockFormatter::Mfa {
+            pid,
+            module,
+            function,
+        }) => call_elixir_formatter(*pid, *module, *function, text),
+    }
+}
+
+fn format_builtin(language: &BuiltinLanguage, text: &str) -> Result<Option<String>, String> {
+    match *language {
+        #[cfg(feature = "json")]
+        BuiltinLanguage::Json => {
+            let config = dprint_plugin_json::configuration::ConfigurationBuilder::new().build();
+            dprint_plugin_json::format_text(text, &config)
+                .map_err(|e| format!("JSON code block formatting failed: {e}"))
+        }
+        #[cfg(feature = "toml")]
+        BuiltinLanguage::Toml => {
+            let config = dprint_plugin_toml::configuration::ConfigurationBuilder::new().build();
+            dprint_plugin_toml::format_text(text, &config)
+                .map_err(|e| format!("TOML code block formatting failed: {e}"))
+        }
+    }
+}
+
+/// Sends the block out to the owning Elixir process and blocks until
+/// `resolve_code_block/2` fills in the reply, mirroring a synchronous call
+/// across the NIF boundary. Uses its own `OwnedEnv` rather than the calling
+/// NIF's `Env` so it can run on a thread rayon spawned for a batch request.
+///
+/// Callers MUST only reach this from a dirty scheduler: the wait below can
+/// take up to `CALLBACK_TIMEOUT`, which would stall the whole BEAM scheduler
+/// pool if run on a normal one. The wait is polled rather than indefinite so
+/// a callback process that crashes mid-format, or one that simply never
+/// calls back, can't hang the worker forever.
+fn call_elixir_formatter(pid: LocalPid, module: Atom, function: Atom, text: &str) -> Result<Option<String>, String> {
+    let pending = ResourceArc::new(PendingCallback::default());
+
+    let sent = OwnedEnv::new().send(&pid, (module, function, pending.clone(), text.to_string()));
+    if !sent {
+        return Err("Elixir callback process is not alive".to_string());
+    }
+
+    let deadline = Instant::now() + CALLBACK_TIMEOUT;
+    let mut reply = pending.0.lock().map_err(|_| "Callback lock poisoned")?;
+
+    while reply.is_none() {
+        let (guard, timeout) = pending
+            .1
+            .wait_timeout(reply, CALLBACK_POLL_INTERVAL)
+            .map_err(|_| "Callback lock poisoned")?;
+        reply = guard;
+
+        if reply.is_some() {
+            break;
+        }
+        if !timeout.timed_out() {
+            continue;
+        }
+        if !OwnedEnv::new().run(|env| env.is_process_alive(pid)) {
+            return Err("Elixir callback process died before replying".to_string());
+        }
+        if Instant::now() >= deadline {
+            return Err("Elixir callback timed out".to_string());
+        }
+    }
+
+    reply.take().expect("loop only exits once reply is Some")
+}
+
+/// Called from Elixir once the MFA callback has produced a result, unblocking
+/// the dirty scheduler thread waiting in `call_elixir_formatter`.
+pub fn resolve_code_block(pending: ResourceArc<PendingCallback>, result: Result<Option<String>, String>) {
+    let mut slot = match pending.0.lock() {
+        Ok(slot) => slot,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    *slot = Some(result);
+    pending.1.notify_one();
+}
@@ -0,0 +1,151 @@
+/// One contiguous change between the original and formatted text.
+/// `start_line` is the 1-based line number in the *original* text where the
+/// change begins (or, for a pure insertion, the line it was inserted before).
+#[derive(rustler::NifMap)]
+#[cfg_attr(test, derive(Debug))]
+pub struct LineHunk {
+    pub start_line: u32,
+    pub removed: Vec<String>,
+    pub added: Vec<String>,
+}
+
+/// Computes a line-level diff between `original` and `formatted`, collapsing
+/// adjacent changed lines into a single hunk. Returns an empty vec when the
+/// two texts are identical line-for-line.
+pub fn diff_lines(original: &str, formatted: &str) -> Vec<LineHunk> {
+    let original_lines: Vec<&str> = original.lines().collect();
+    let formatted_lines: Vec<&str> = formatted.lines().collect();
+    let table = lcs_table(&original_lines, &formatted_lines);
+
+    let mut hunks = Vec::new();
+    let mut current: Option<LineHunk> = None;
+    let (mut i, mut j) = (0usize, 0usize);
+
+    while i < original_lines.len() || j < formatted_lines.len() {
+        let is_equal =
+            i < original_lines.len() && j < formatted_lines.len() && original_lines[i] == formatted_lines[j];
+
+        if is_equal {
+            if let Some(hunk) = current.take() {
+                hunks.push(hunk);
+            }
+            i += 1;
+            j += 1;
+            continue;
+        }
+
+        let take_removed =
+            j >= formatted_lines.len() || (i < original_lines.len() && table[i + 1][j] >= table[i][j + 1]);
+
+        let hunk = current.get_or_insert_with(|| LineHunk {
+            start_line: (i + 1) as u32,
+            removed: Vec::new(),
+            added: Vec::new(),
+        });
+
+        if take_removed {
+            hunk.removed.push(original_lines[i].to_string());
+            i += 1;
+        } else {
+            hunk.added.push(formatted_lines[j].to_string());
+            j += 1;
+        }
+    }
+
+    if let Some(hunk) = current.take() {
+        hunks.push(hunk);
+    }
+
+    hunks
+}
+
+/// Longest-common-subsequence table over lines, computed with a simple DP
+/// pass. `table[i][j]` holds the LCS length of `a[i..]` and `b[j..]`.
+fn lcs_table(a: &[&str], b: &[&str]) -> Vec<Vec<u32>> {
+    let (n, m) = (a.len(), b.len());
+    let mut table = vec![vec![0u32; m + 1]; n + 1];
+
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            table[i][j] = if a[i] == b[j] {
+                table[i + 1][j + 1] + 1
+            } else {
+                table[i + 1][j].max(table[i][j + 1])
+            };
+        }
+    }
+
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hunk(start_line: u32, removed: &[&str], added: &[&str]) -> LineHunk {
+        LineHunk {
+            start_line,
+            removed: removed.iter().map(|s| s.to_string()).collect(),
+            added: added.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    fn assert_hunks(original: &str, formatted: &str, expected: Vec<LineHunk>) {
+        let hunks = diff_lines(original, formatted);
+        assert_eq!(hunks.len(), expected.len(), "hunk count mismatch: {hunks:?}");
+        for (actual, expected) in hunks.iter().zip(&expected) {
+            assert_eq!(actual.start_line, expected.start_line);
+            assert_eq!(actual.removed, expected.removed);
+            assert_eq!(actual.added, expected.added);
+        }
+    }
+
+    #[test]
+    fn identical_text_has_no_hunks() {
+        assert_hunks("a\nb\nc\n", "a\nb\nc\n", vec![]);
+    }
+
+    #[test]
+    fn insert_at_start() {
+        assert_hunks("a\nb\n", "x\na\nb\n", vec![hunk(1, &[], &["x"])]);
+    }
+
+    #[test]
+    fn insert_at_end() {
+        assert_hunks("a\nb\n", "a\nb\nx\n", vec![hunk(3, &[], &["x"])]);
+    }
+
+    #[test]
+    fn insert_in_middle() {
+        assert_hunks("a\nb\nc\n", "a\nx\nb\nc\n", vec![hunk(2, &[], &["x"])]);
+    }
+
+    #[test]
+    fn delete_at_start() {
+        assert_hunks("a\nb\nc\n", "b\nc\n", vec![hunk(1, &["a"], &[])]);
+    }
+
+    #[test]
+    fn delete_at_end() {
+        assert_hunks("a\nb\nc\n", "a\nb\n", vec![hunk(3, &["c"], &[])]);
+    }
+
+    #[test]
+    fn delete_in_middle() {
+        assert_hunks("a\nb\nc\n", "a\nc\n", vec![hunk(2, &["b"], &[])]);
+    }
+
+    #[test]
+    fn replace_in_middle() {
+        assert_hunks("a\nb\nc\n", "a\nx\nc\n", vec![hunk(2, &["b"], &["x"])]);
+    }
+
+    #[test]
+    fn multiple_separate_hunks() {
+        assert_hunks(
+            "a\nb\nc\nd\ne\n",
+            "x\nb\nc\ny\ne\n",
+            vec![hunk(1, &["a"], &["x"]), hunk(4, &["d"], &["y"])],
+        );
+    }
+}
@@ -0,0 +1,118 @@
+use crate::code_block::{format_code_block, CodeBlockFormatters};
+use crate::diff::{diff_lines, LineHunk};
+use crate::range::{restrict_to_ranges, LineRange};
+use dprint_plugin_markdown::{configuration::Configuration, format_text};
+use std::cell::RefCell;
+
+/// A fenced code block that failed to format, paired with its language tag.
+/// Such a block is left verbatim in the output (mirroring rustfmt leaving an
+/// unformattable doc-comment snippet untouched) rather than failing the whole
+/// document, so callers get both a usable result and a report of what didn't
+/// format.
+pub type CodeBlockErrors = Vec<(String, String)>;
+
+/// Runs dprint's `format_text` with a callback that never fails the whole
+/// document: a broken code block is reported into `errors` and left
+/// unformatted instead of aborting every other paragraph, list, and code
+/// block alongside it. Only a dprint-level failure (e.g. invalid markdown
+/// structure) reaches the `Err` case here.
+fn format_collecting_errors(
+    text: &str,
+    config: &Configuration,
+    formatters: &CodeBlockFormatters,
+) -> Result<(Option<String>, CodeBlockErrors), String> {
+    let errors = RefCell::new(Vec::new());
+
+    let formatted = format_text(text, config, |language, block_text, _| {
+        match format_code_block(language, block_text, formatters) {
+            Ok(result) => Ok(result),
+            Err(reason) => {
+                errors.borrow_mut().push((language.to_string(), reason));
+                Ok(None)
+            }
+        }
+    })
+    .map_err(|e| format!("Formatting failed: {e}"))?;
+
+    Ok((formatted, errors.into_inner()))
+}
+
+/// Formats one document against an already-built `Configuration` and set of
+/// code block formatters, restricting the result to `ranges` (empty means
+/// "format everything"). Shared between `format_markdown` and
+/// `format_markdown_batch` so the batch NIF only builds these once per call.
+/// Any code blocks that failed to format are reported alongside the result
+/// rather than failing the whole call.
+pub fn apply_format(
+    text: &str,
+    config: &Configuration,
+    formatters: &CodeBlockFormatters,
+    ranges: &[LineRange],
+) -> Result<(String, CodeBlockErrors), String> {
+    let (formatted, errors) = format_collecting_errors(text, config, formatters)?;
+
+    let result = match formatted {
+        None => text.to_string(),
+        Some(formatted) => restrict_to_ranges(text, &formatted, ranges),
+    };
+
+    Ok((result, errors))
+}
+
+/// Result of checking whether a document is already formatted.
+pub enum CheckOutcome {
+    Unchanged,
+    WouldReformat(u32),
+}
+
+/// Checks whether `text` is already formatted against `config`, without
+/// paying to build the restricted-range output `apply_format` would produce.
+/// Only a genuine dprint-level formatting failure reaches the `Err` case —
+/// callers are responsible for surfacing that distinctly from
+/// `CheckOutcome::WouldReformat`, which is the legitimate "needs formatting"
+/// result, not an error. Code blocks that failed to format are reported
+/// alongside the outcome rather than failing the call.
+pub fn check_format(
+    text: &str,
+    config: &Configuration,
+    formatters: &CodeBlockFormatters,
+) -> Result<(CheckOutcome, CodeBlockErrors), String> {
+    let (formatted, errors) = format_collecting_errors(text, config, formatters)?;
+
+    let outcome = match formatted {
+        None => CheckOutcome::Unchanged,
+        Some(formatted) => CheckOutcome::WouldReformat(count_differing_lines(text, &formatted)),
+    };
+
+    Ok((outcome, errors))
+}
+
+/// Formats `text` and reports the result as line hunks rather than the whole
+/// document, alongside any code blocks that failed to format. Shared by both
+/// front-ends' `format_markdown_diff`.
+pub fn format_diff(
+    text: &str,
+    config: &Configuration,
+    formatters: &CodeBlockFormatters,
+) -> Result<(Vec<LineHunk>, CodeBlockErrors), String> {
+    let (formatted, errors) = format_collecting_errors(text, config, formatters)?;
+
+    let hunks = match formatted {
+        None => Vec::new(),
+        Some(formatted) => diff_lines(text, &formatted),
+    };
+
+    Ok((hunks, errors))
+}
+
+/// Counts lines that differ between the original and formatted text, derived
+/// from the same LCS-based diff `diff_lines` uses rather than a positional
+/// comparison: a naive zip of `original_lines[i]` against `formatted_lines[i]`
+/// misaligns everything after a single inserted or removed line, so nearly
+/// the whole document would count as "differing".
+pub fn count_differing_lines(original: &str, formatted: &str) -> u32 {
+    diff_lines(original, formatted)
+        .iter()
+        .map(|hunk| hunk.removed.len().max(hunk.added.len()) as u32)
+        .sum()
+}
@@ -0,0 +1,18 @@
+//! Shared formatting, diff, range-restriction and code-block-callback logic
+//! used by both NIF front-ends (`dprint_markdown_formatter`, which takes a
+//! `HashMap<Atom, Term>` config, and `dprint_markdown_formatter_nif`, which
+//! takes a keyword-list `Term`). Keeping this in one crate means a fix here
+//! benefits both front-ends instead of needing to be copied twice.
+
+pub mod code_block;
+pub mod diff;
+pub mod format;
+pub mod range;
+
+pub use code_block::{
+    build_code_block_formatters, format_code_block, parse_code_block_formatters, resolve_code_block,
+    CodeBlockFormatters, PendingCallback,
+};
+pub use diff::{diff_lines, LineHunk};
+pub use format::{apply_format, check_format, count_differing_lines, format_diff, CheckOutcome, CodeBlockErrors};
+pub use range::{restrict_to_ranges, LineRange};
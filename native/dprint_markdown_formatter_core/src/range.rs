@@ -0,0 +1,134 @@
+use crate::diff::{diff_lines, LineHunk};
+
+/// A 1-based, inclusive line range requested by the caller (rustfmt calls
+/// this `FileLines`).
+pub type LineRange = (u32, u32);
+
+/// Re-runs the whole-document diff against `ranges` and reverts any hunk
+/// whose original line span falls outside every requested range, so editors
+/// can format just the paragraph or list under the cursor. An empty `ranges`
+/// means "format everything", matching `format_markdown`'s prior behavior.
+pub fn restrict_to_ranges(original: &str, formatted: &str, ranges: &[LineRange]) -> String {
+    if ranges.is_empty() {
+        return formatted.to_string();
+    }
+
+    let original_lines: Vec<&str> = original.lines().collect();
+    let hunks = diff_lines(original, formatted);
+
+    let mut result: Vec<&str> = Vec::new();
+    let mut cursor = 0usize;
+
+    for hunk in &hunks {
+        let hunk_start = (hunk.start_line - 1) as usize; // 0-based index into original_lines
+        result.extend_from_slice(&original_lines[cursor..hunk_start]);
+
+        if hunk_intersects(hunk, ranges, original_lines.len()) {
+            result.extend(hunk.added.iter().map(String::as_str));
+        } else {
+            result.extend(hunk.removed.iter().map(String::as_str));
+        }
+
+        cursor = hunk_start + hunk.removed.len();
+    }
+
+    result.extend_from_slice(&original_lines[cursor..]);
+
+    let mut text = result.join("\n");
+    if original.ends_with('\n') {
+        text.push('\n');
+    }
+    text
+}
+
+/// A hunk with no removed lines is a pure insertion; anchor it on the
+/// original line it would be inserted before, clamped to
+/// `original_lines_len` (the last original line) for an insertion at end of
+/// file, so straddling a range boundary is judged by where the change
+/// actually lands rather than by an empty span one line past the end of the
+/// document.
+fn hunk_intersects(hunk: &LineHunk, ranges: &[LineRange], original_lines_len: usize) -> bool {
+    let (span_start, span_end) = if hunk.removed.is_empty() {
+        let anchor = hunk.start_line.min(original_lines_len as u32).max(1);
+        (anchor, anchor)
+    } else {
+        (hunk.start_line, hunk.start_line + hunk.removed.len() as u32 - 1)
+    };
+
+    ranges
+        .iter()
+        .any(|&(start, end)| start <= span_end && span_start <= end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_ranges_means_format_everything() {
+        let original = "a\nb\nc\n";
+        let formatted = "x\nb\nc\n";
+        assert_eq!(restrict_to_ranges(original, formatted, &[]), formatted);
+    }
+
+    #[test]
+    fn range_covering_the_change_keeps_it() {
+        let original = "a\nb\nc\n";
+        let formatted = "x\nb\nc\n";
+        assert_eq!(restrict_to_ranges(original, formatted, &[(1, 1)]), formatted);
+    }
+
+    #[test]
+    fn range_not_covering_the_change_reverts_it() {
+        let original = "a\nb\nc\n";
+        let formatted = "x\nb\nc\n";
+        assert_eq!(restrict_to_ranges(original, formatted, &[(2, 3)]), original);
+    }
+
+    #[test]
+    fn range_covering_one_of_two_changes_reverts_only_the_other() {
+        let original = "a\nb\nc\nd\ne\n";
+        let formatted = "x\nb\nc\ny\ne\n";
+        assert_eq!(restrict_to_ranges(original, formatted, &[(1, 1)]), "x\nb\nc\nd\ne\n");
+        assert_eq!(restrict_to_ranges(original, formatted, &[(4, 4)]), "a\nb\nc\ny\ne\n");
+    }
+
+    #[test]
+    fn range_covering_middle_deletion_keeps_it() {
+        let original = "a\nb\nc\n";
+        let formatted = "a\nc\n";
+        assert_eq!(restrict_to_ranges(original, formatted, &[(2, 2)]), formatted);
+    }
+
+    #[test]
+    fn range_not_covering_middle_deletion_reverts_it() {
+        let original = "a\nb\nc\n";
+        let formatted = "a\nc\n";
+        assert_eq!(restrict_to_ranges(original, formatted, &[(1, 1)]), original);
+    }
+
+    #[test]
+    fn eof_insertion_anchored_to_last_original_line_is_kept_by_a_range_covering_it() {
+        let original = "a\nb\n";
+        let formatted = "a\nb\nx\n";
+        assert_eq!(restrict_to_ranges(original, formatted, &[(2, 2)]), formatted);
+    }
+
+    #[test]
+    fn eof_insertion_is_reverted_by_a_range_not_covering_the_last_original_line() {
+        let original = "a\nb\n";
+        let formatted = "a\nb\nx\n";
+        assert_eq!(restrict_to_ranges(original, formatted, &[(1, 1)]), original);
+    }
+
+    #[test]
+    fn hunk_intersects_clamps_eof_insertion_anchor_within_bounds() {
+        let insertion_at_eof = LineHunk {
+            start_line: 3,
+            removed: Vec::new(),
+            added: vec!["x".to_string()],
+        };
+        assert!(hunk_intersects(&insertion_at_eof, &[(2, 2)], 2));
+        assert!(!hunk_intersects(&insertion_at_eof, &[(1, 1)], 2));
+    }
+}
@@ -1,6 +1,11 @@
 use dprint_core::configuration::NewLineKind;
-use dprint_plugin_markdown::{configuration::Configuration, format_text};
-use rustler::{Atom, Term};
+use dprint_markdown_formatter_core::{
+    apply_format, check_format, format_diff, parse_code_block_formatters, resolve_code_block, CheckOutcome,
+    CodeBlockErrors, LineHunk, LineRange, PendingCallback,
+};
+use dprint_plugin_markdown::configuration::Configuration;
+use rayon::prelude::*;
+use rustler::{Atom, Encoder, Env, ResourceArc, Term};
 
 // Define atom constants for option matching
 rustler::atoms! {
@@ -10,16 +15,45 @@ rustler::atoms! {
     strong_kind,
     new_line_kind,
     unordered_list_kind,
+    code_block_formatters,
+    file_lines,
+    unchanged,
+    would_reformat,
+    format_error,
+    code_block_errors,
+}
+
+/// Error returned from `check_markdown`: either the document genuinely needs
+/// reformatting (the non-error outcome `format_markdown` would also
+/// produce), a code block failed to format, or the config/formatting itself
+/// is broken. Keeping these distinct means a caller can't mistake "your
+/// code_block_formatters entry is invalid" for "just run the formatter".
+enum CheckError {
+    FormatError(String),
+    WouldReformat(u32),
+    CodeBlockErrors(CodeBlockErrors),
+}
+
+impl Encoder for CheckError {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        match self {
+            CheckError::FormatError(reason) => (format_error(), reason.as_str()).encode(env),
+            CheckError::WouldReformat(count) => (would_reformat(), *count).encode(env),
+            CheckError::CodeBlockErrors(errors) => (code_block_errors(), errors.as_slice()).encode(env),
+        }
+    }
 }
 
 #[derive(Debug, Default)]
-struct FormatOptions {
+struct FormatOptions<'a> {
     line_width: Option<u32>,
     text_wrap: Option<String>,
     emphasis_kind: Option<String>,
     strong_kind: Option<String>,
     new_line_kind: Option<String>,
     unordered_list_kind: Option<String>,
+    code_block_formatters: Option<Term<'a>>,
+    file_lines: Vec<LineRange>,
 }
 
 fn parse_options(options: Term) -> FormatOptions {
@@ -52,6 +86,12 @@ fn parse_options(options: Term) -> FormatOptions {
                 if let Ok(kind) = value.decode::<String>() {
                     format_options.unordered_list_kind = Some(kind);
                 }
+            } else if key_atom == code_block_formatters() {
+                format_options.code_block_formatters = Some(value);
+            } else if key_atom == file_lines() {
+                if let Ok(ranges) = value.decode::<Vec<LineRange>>() {
+                    format_options.file_lines = ranges;
+                }
             }
         }
     } else if let Ok(keyword_list) = options.decode::<Vec<(String, Term)>>() {
@@ -88,6 +128,14 @@ fn parse_options(options: Term) -> FormatOptions {
                         format_options.unordered_list_kind = Some(kind);
                     }
                 }
+                "code_block_formatters" => {
+                    format_options.code_block_formatters = Some(value);
+                }
+                "file_lines" => {
+                    if let Ok(ranges) = value.decode::<Vec<LineRange>>() {
+                        format_options.file_lines = ranges;
+                    }
+                }
                 _ => {} // Ignore unknown options
             }
         }
@@ -96,12 +144,9 @@ fn parse_options(options: Term) -> FormatOptions {
     format_options
 }
 
-#[rustler::nif]
-fn format_markdown(text: String, options: Term) -> Result<String, String> {
-    let opts = parse_options(options);
-
-    // Create configuration with defaults, overridden by options
-    let config = Configuration {
+/// Builds a dprint Configuration with defaults, overridden by options
+fn build_config(opts: &FormatOptions) -> Configuration {
+    Configuration {
         line_width: opts.line_width.unwrap_or(80),
         text_wrap: match opts.text_wrap.as_deref() {
             Some("never") => dprint_plugin_markdown::configuration::TextWrap::Never,
@@ -122,23 +167,117 @@ fn format_markdown(text: String, options: Term) -> Result<String, String> {
             _ => NewLineKind::Auto,
         },
         unordered_list_kind: match opts.unordered_list_kind.as_deref() {
-            Some("asterisks") => {
-                dprint_plugin_markdown::configuration::UnorderedListKind::Asterisks
-            }
+            Some("asterisks") => dprint_plugin_markdown::configuration::UnorderedListKind::Asterisks,
             _ => dprint_plugin_markdown::configuration::UnorderedListKind::Dashes,
         },
         ignore_directive: "dprint-ignore".to_string(),
         ignore_start_directive: "dprint-ignore-start".to_string(),
         ignore_end_directive: "dprint-ignore-end".to_string(),
         ignore_file_directive: "dprint-ignore-file".to_string(),
-    };
+    }
+}
+
+/// Runs on a dirty scheduler because formatting a fenced code block may call
+/// back into Elixir via an MFA formatter and block the thread waiting for
+/// `resolve_code_block_callback`, which must never happen on a normal
+/// scheduler thread. Returns the formatted text alongside any code blocks
+/// that failed to format (and were left verbatim) as `(language, reason)`
+/// pairs, rather than failing the whole document over one bad block.
+#[rustler::nif(schedule = "DirtyIo")]
+fn format_markdown(text: String, options: Term) -> Result<(String, CodeBlockErrors), String> {
+    let opts = parse_options(options);
+    let formatters = parse_code_block_formatters(opts.code_block_formatters);
+    let config = build_config(&opts);
+
+    apply_format(&text, &config, &formatters, &opts.file_lines)
+}
+
+/// Formats many documents against one shared configuration, amortizing the
+/// cost of crossing the NIF boundary and building the `Configuration` once.
+/// Runs on a dirty CPU scheduler and fans entries out over a `rayon` thread
+/// pool; one entry's failure is reported alongside the others rather than
+/// aborting the whole batch.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn format_markdown_batch(
+    entries: Vec<(String, String)>,
+    options: Term,
+) -> Result<Vec<(String, Result<(String, CodeBlockErrors), String>)>, String> {
+    let opts = parse_options(options);
+    let formatters = parse_code_block_formatters(opts.code_block_formatters);
+    let config = build_config(&opts);
+
+    let results = entries
+        .into_par_iter()
+        .map(|(id, text)| {
+            let result = if text.is_empty() {
+                Ok((text, Vec::new()))
+            } else {
+                apply_format(&text, &config, &formatters, &opts.file_lines)
+            };
+            (id, result)
+        })
+        .collect();
+
+    Ok(results)
+}
 
-    // Format the text using dprint-plugin-markdown
-    match format_text(&text, &config, |_, _, _| Ok(None)) {
-        Ok(Some(formatted)) => Ok(formatted),
-        Ok(None) => Ok(text), // No changes needed
-        Err(e) => Err(format!("Formatting error: {}", e)),
+/// Reports whether `text` is already formatted, without shipping the
+/// rewritten document back across the NIF boundary. Returns `:unchanged` on
+/// success, `{:would_reformat, differing_line_count}` as the error when
+/// formatting would change the text, `{:code_block_errors, [{language,
+/// reason}]}` when a fenced code block failed to format, or `{:format_error,
+/// reason}` for the same config or formatting failures `format_markdown`
+/// would surface for these inputs. Dirty-scheduled for the same reason as
+/// `format_markdown`.
+#[rustler::nif(schedule = "DirtyIo")]
+fn check_markdown(text: String, options: Term) -> Result<Atom, CheckError> {
+    if text.is_empty() {
+        return Ok(unchanged());
     }
+
+    let opts = parse_options(options);
+    let formatters = parse_code_block_formatters(opts.code_block_formatters);
+    let config = build_config(&opts);
+
+    let (outcome, errors) = check_format(&text, &config, &formatters).map_err(CheckError::FormatError)?;
+    if !errors.is_empty() {
+        return Err(CheckError::CodeBlockErrors(errors));
+    }
+
+    match outcome {
+        CheckOutcome::Unchanged => Ok(unchanged()),
+        CheckOutcome::WouldReformat(count) => Err(CheckError::WouldReformat(count)),
+    }
+}
+
+/// Formats `text` and returns the changes as a list of line hunks rather than
+/// the whole document, so editors and review tools can apply or display
+/// edits precisely without re-diffing the full text themselves, alongside any
+/// code blocks that failed to format as `(language, reason)` pairs. Returns
+/// an empty hunk list when formatting wouldn't change anything.
+/// Dirty-scheduled for the same reason as `format_markdown`.
+#[rustler::nif(schedule = "DirtyIo")]
+fn format_markdown_diff(text: String, options: Term) -> Result<(Vec<LineHunk>, CodeBlockErrors), String> {
+    if text.is_empty() {
+        return Ok((Vec::new(), Vec::new()));
+    }
+
+    let opts = parse_options(options);
+    let formatters = parse_code_block_formatters(opts.code_block_formatters);
+    let config = build_config(&opts);
+
+    format_diff(&text, &config, &formatters)
+}
+
+/// Resolves a pending Elixir code-block callback, unblocking the dirty
+/// scheduler thread that dispatched it.
+#[rustler::nif]
+fn resolve_code_block_callback(pending: ResourceArc<PendingCallback>, result: Result<Option<String>, String>) {
+    resolve_code_block(pending, result);
+}
+
+fn load(env: Env, _info: Term) -> bool {
+    env.register::<PendingCallback>().is_ok()
 }
 
-rustler::init!("Elixir.DprintMarkdownFormatter.Native");
+rustler::init!("Elixir.DprintMarkdownFormatter.Native", load = load);